@@ -1,23 +1,61 @@
-use rustyline::{error::ReadlineError, DefaultEditor};
+use clap::Parser;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Context, Editor, Helper};
+use std::borrow::Cow;
+use std::cell::RefCell;
 use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use thiserror::Error;
 
 struct Machine {
-    stack: Vec<i64>,
+    stack: Vec<Cell>,
     definitions: HashMap<String, Definition>,
+    /// Frames of `(index, limit)` for currently open `DO`/`LOOP`s.
+    loop_stack: Vec<(i64, i64)>,
+    /// Linear memory for `variable` cells and `allot`ted space, addressed by
+    /// index. `constant` values live in their definitions, not here.
+    memory: Vec<i64>,
+    /// Canonicalized paths of files currently being `include`d, innermost
+    /// last, used to resolve relative includes and reject cycles.
+    include_stack: Vec<PathBuf>,
+}
+
+/// A value on the data stack: either a number or a string, so that `."`,
+/// `s"` and friends can share the stack with ordinary arithmetic.
+#[derive(Debug, Clone, PartialEq)]
+enum Cell {
+    Int(i64),
+    Str(String),
+}
+
+impl Cell {
+    fn as_int(&self) -> Result<i64, ForthError> {
+        match self {
+            Cell::Int(n) => Ok(*n),
+            Cell::Str(_) => Err(ForthError::TypeMismatch),
+        }
+    }
 }
 
 #[derive(Clone)]
 enum Definition {
-    Native(fn(&mut Vec<i64>) -> Result<(), ForthError>),
-    Tokens(String),
+    Native(fn(&mut Vec<Cell>) -> Result<(), ForthError>),
+    NativeMem(fn(&mut Vec<Cell>, &mut Vec<i64>) -> Result<(), ForthError>),
+    Compiled(Rc<Vec<Instr>>),
 }
 
 impl std::fmt::Debug for Definition {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Definition::Native(_) => write!(f, "Native"),
-            Definition::Tokens(s) => write!(f, "Tokens({})", s),
+            Definition::NativeMem(_) => write!(f, "NativeMem"),
+            Definition::Compiled(instrs) => write!(f, "Compiled({} instrs)", instrs.len()),
         }
     }
 }
@@ -28,6 +66,12 @@ enum Keyword {
     Else,
     Then,
     Do,
+    Loop,
+    I,
+    Begin,
+    While,
+    Repeat,
+    Until,
 }
 
 impl TryFrom<&str> for Keyword {
@@ -38,6 +82,12 @@ impl TryFrom<&str> for Keyword {
             "else" => Ok(Keyword::Else),
             "then" => Ok(Keyword::Then),
             "do" => Ok(Keyword::Do),
+            "loop" => Ok(Keyword::Loop),
+            "i" => Ok(Keyword::I),
+            "begin" => Ok(Keyword::Begin),
+            "while" => Ok(Keyword::While),
+            "repeat" => Ok(Keyword::Repeat),
+            "until" => Ok(Keyword::Until),
             _ => Err(()),
         }
     }
@@ -46,8 +96,174 @@ impl TryFrom<&str> for Keyword {
 #[derive(Debug, Clone)]
 enum Token {
     Number(i64),
-    Op(Definition),
+    /// A word by name, resolved against `definitions` when it runs rather
+    /// than when it's lexed, so a definition can call itself or a word
+    /// defined later (recursion, mutual recursion, forward references).
+    Word(String),
     Keyword(Keyword),
+    /// `." ..."` — prints the literal immediately when executed.
+    PrintLit(String),
+    /// `s" ..."` — pushes the literal onto the stack as a `Cell::Str`.
+    PushLit(String),
+}
+
+/// One raw word out of the input, with quoted string spans (`." ..."`,
+/// `s" ..."`) already collapsed into a single unit.
+enum RawWord {
+    Word(String),
+    PrintLit(String),
+    PushLit(String),
+}
+
+/// Any non-graphic character is a word separator: ordinary whitespace, but
+/// also control characters like NUL/SOH that `str::split_whitespace` leaves
+/// alone.
+fn is_separator(c: char) -> bool {
+    c.is_whitespace() || c.is_control()
+}
+
+/// Splits `input` on separator characters like before, except a `."` or
+/// `s"` word swallows every following word up to (and including) one ending
+/// in `"`, joining them back with spaces into a single literal.
+fn tokenize_raw(input: &str) -> Result<Vec<RawWord>, ForthError> {
+    let mut out = vec![];
+    let mut words = input.split(is_separator).filter(|w| !w.is_empty());
+
+    while let Some(word) = words.next() {
+        match word.to_lowercase().as_str() {
+            ".\"" => out.push(RawWord::PrintLit(capture_quoted(&mut words)?)),
+            "s\"" => out.push(RawWord::PushLit(capture_quoted(&mut words)?)),
+            _ => out.push(RawWord::Word(word.to_owned())),
+        }
+    }
+    Ok(out)
+}
+
+/// Joins words with spaces until one ends in `"`, dropping that trailing
+/// quote from the result.
+fn capture_quoted<'a>(words: &mut impl Iterator<Item = &'a str>) -> Result<String, ForthError> {
+    let mut parts = vec![];
+    loop {
+        let word = words.next().ok_or(ForthError::UnterminatedString)?;
+        match word.strip_suffix('"') {
+            Some(stripped) => {
+                parts.push(stripped);
+                break;
+            }
+            None => parts.push(word),
+        }
+    }
+    Ok(parts.join(" "))
+}
+
+/// A single flat bytecode instruction. Produced by `compile` and executed by
+/// `Machine::run_instrs`, which walks the vector with a program counter
+/// instead of recursing through the token stream.
+#[derive(Debug, Clone)]
+enum Instr {
+    Push(Cell),
+    /// Looks `name` up in `definitions` and runs it. Resolved at call time,
+    /// not compile time, so forward/self/mutual recursion work.
+    Call(String),
+    Jump(usize),
+    JumpUnless(usize),
+    /// Pops `start` then `limit` off the data stack and opens a loop frame.
+    LoopEnter,
+    /// Increments the current loop frame's index, jumping back to the given
+    /// body start while it is still below the limit, otherwise closing the
+    /// frame and falling through.
+    LoopNext(usize),
+    /// Pushes the current loop frame's index (the `I` word).
+    PushIndex,
+    /// Prints a `."` literal immediately, without touching the stack.
+    PrintLiteral(String),
+}
+
+/// Tracks an unresolved control-flow construct while compiling, so its jump
+/// target(s) can be patched once the matching closing word is seen.
+enum OpenBlock {
+    If(usize),
+    Else(usize),
+    Do(usize),
+    Begin(usize),
+    While { begin: usize, jump_idx: usize },
+}
+
+/// Lowers a token stream into flat bytecode, resolving `if`/`else`/`then`,
+/// `do`/`loop` and `begin`/`while`/`repeat`/`until` into jumps at compile
+/// time rather than interpreting them recursively.
+fn compile(tokens: Vec<Token>) -> Result<Vec<Instr>, ForthError> {
+    let mut instrs = vec![];
+    let mut control_stack: Vec<OpenBlock> = vec![];
+
+    for token in tokens {
+        match token {
+            Token::Number(n) => instrs.push(Instr::Push(Cell::Int(n))),
+            Token::Word(name) => instrs.push(Instr::Call(name)),
+            Token::PrintLit(s) => instrs.push(Instr::PrintLiteral(s)),
+            Token::PushLit(s) => instrs.push(Instr::Push(Cell::Str(s))),
+            Token::Keyword(Keyword::If) => {
+                control_stack.push(OpenBlock::If(instrs.len()));
+                instrs.push(Instr::JumpUnless(0)); // patched at else/then
+            }
+            Token::Keyword(Keyword::Else) => match control_stack.pop() {
+                Some(OpenBlock::If(if_idx)) => {
+                    let else_idx = instrs.len();
+                    instrs.push(Instr::Jump(0)); // patched at then
+                    instrs[if_idx] = Instr::JumpUnless(instrs.len());
+                    control_stack.push(OpenBlock::Else(else_idx));
+                }
+                _ => return Err(ForthError::UnbalancedIf),
+            },
+            Token::Keyword(Keyword::Then) => match control_stack.pop() {
+                Some(OpenBlock::If(if_idx)) => {
+                    instrs[if_idx] = Instr::JumpUnless(instrs.len());
+                }
+                Some(OpenBlock::Else(else_idx)) => {
+                    instrs[else_idx] = Instr::Jump(instrs.len());
+                }
+                _ => return Err(ForthError::UnbalancedIf),
+            },
+            Token::Keyword(Keyword::Do) => {
+                instrs.push(Instr::LoopEnter);
+                control_stack.push(OpenBlock::Do(instrs.len()));
+            }
+            Token::Keyword(Keyword::Loop) => match control_stack.pop() {
+                Some(OpenBlock::Do(body_start)) => instrs.push(Instr::LoopNext(body_start)),
+                _ => return Err(ForthError::UnbalancedLoop),
+            },
+            Token::Keyword(Keyword::I) => instrs.push(Instr::PushIndex),
+            Token::Keyword(Keyword::Begin) => control_stack.push(OpenBlock::Begin(instrs.len())),
+            Token::Keyword(Keyword::While) => match control_stack.pop() {
+                Some(OpenBlock::Begin(begin)) => {
+                    let jump_idx = instrs.len();
+                    instrs.push(Instr::JumpUnless(0)); // patched at repeat
+                    control_stack.push(OpenBlock::While { begin, jump_idx });
+                }
+                _ => return Err(ForthError::UnbalancedRepeat),
+            },
+            Token::Keyword(Keyword::Repeat) => match control_stack.pop() {
+                Some(OpenBlock::While { begin, jump_idx }) => {
+                    instrs.push(Instr::Jump(begin));
+                    instrs[jump_idx] = Instr::JumpUnless(instrs.len());
+                }
+                _ => return Err(ForthError::UnbalancedRepeat),
+            },
+            Token::Keyword(Keyword::Until) => match control_stack.pop() {
+                Some(OpenBlock::Begin(begin)) => instrs.push(Instr::JumpUnless(begin)),
+                _ => return Err(ForthError::UnbalancedUntil),
+            },
+        }
+    }
+
+    match control_stack.pop() {
+        None => Ok(instrs),
+        Some(OpenBlock::Do(_)) => Err(ForthError::UnbalancedLoop),
+        Some(OpenBlock::Begin(_)) | Some(OpenBlock::While { .. }) => {
+            Err(ForthError::UnbalancedRepeat)
+        }
+        Some(OpenBlock::If(_)) | Some(OpenBlock::Else(_)) => Err(ForthError::UnbalancedIf),
+    }
 }
 
 #[derive(Debug, Error)]
@@ -58,78 +274,141 @@ enum ForthError {
     StackUnderflow,
     #[error("Attempted to divide by zero")]
     DivByZero,
-    #[error("New 'if' keyword before previous conditional completed.")]
+    #[error("'if'/'else'/'then' are unbalanced")]
     UnbalancedIf,
+    #[error("'do'/'loop' are unbalanced")]
+    UnbalancedLoop,
+    #[error("'begin'/'while'/'repeat' are unbalanced")]
+    UnbalancedRepeat,
+    #[error("'until' without a matching 'begin'")]
+    UnbalancedUntil,
+    #[error("malformed {0} definition")]
+    MalformedDefinition(&'static str),
+    #[error("address out of bounds")]
+    InvalidAddress,
+    #[error("word applied to a value of the wrong type")]
+    TypeMismatch,
+    #[error("string literal is missing its closing quote")]
+    UnterminatedString,
+    #[error("include {0} failed: {1}")]
+    IncludeError(String, String),
 }
 
-fn add(stack: &mut Vec<i64>) -> Result<(), ForthError> {
-    let lhs = stack.pop();
-    let rhs = stack.pop();
-    if let (Some(lhs), Some(rhs)) = (lhs, rhs) {
-        stack.push(lhs + rhs);
-        Ok(())
-    } else {
-        Err(ForthError::StackUnderflow)
-    }
+fn add(stack: &mut Vec<Cell>) -> Result<(), ForthError> {
+    let lhs = stack.pop().ok_or(ForthError::StackUnderflow)?.as_int()?;
+    let rhs = stack.pop().ok_or(ForthError::StackUnderflow)?.as_int()?;
+    stack.push(Cell::Int(lhs + rhs));
+    Ok(())
 }
 
-fn sub(stack: &mut Vec<i64>) -> Result<(), ForthError> {
-    let rhs = stack.pop();
-    let lhs = stack.pop();
-    if let (Some(lhs), Some(rhs)) = (lhs, rhs) {
-        stack.push(lhs - rhs);
-        Ok(())
-    } else {
-        Err(ForthError::StackUnderflow)
-    }
+fn sub(stack: &mut Vec<Cell>) -> Result<(), ForthError> {
+    let rhs = stack.pop().ok_or(ForthError::StackUnderflow)?.as_int()?;
+    let lhs = stack.pop().ok_or(ForthError::StackUnderflow)?.as_int()?;
+    stack.push(Cell::Int(lhs - rhs));
+    Ok(())
 }
 
-fn mul(stack: &mut Vec<i64>) -> Result<(), ForthError> {
-    let lhs = stack.pop();
-    let rhs = stack.pop();
-    if let (Some(lhs), Some(rhs)) = (lhs, rhs) {
-        stack.push(lhs * rhs);
-        Ok(())
-    } else {
-        Err(ForthError::StackUnderflow)
-    }
+fn mul(stack: &mut Vec<Cell>) -> Result<(), ForthError> {
+    let lhs = stack.pop().ok_or(ForthError::StackUnderflow)?.as_int()?;
+    let rhs = stack.pop().ok_or(ForthError::StackUnderflow)?.as_int()?;
+    stack.push(Cell::Int(lhs * rhs));
+    Ok(())
 }
 
-fn div(stack: &mut Vec<i64>) -> Result<(), ForthError> {
-    let rhs = stack.pop();
-    let lhs = stack.pop();
-    if let (Some(lhs), Some(rhs)) = (lhs, rhs) {
-        let res = lhs.checked_div(rhs);
-        match res {
-            Some(n) => stack.push(n),
-            None => return Err(ForthError::DivByZero),
-        }
-        Ok(())
-    } else {
-        Err(ForthError::StackUnderflow)
-    }
+fn div(stack: &mut Vec<Cell>) -> Result<(), ForthError> {
+    let rhs = stack.pop().ok_or(ForthError::StackUnderflow)?.as_int()?;
+    let lhs = stack.pop().ok_or(ForthError::StackUnderflow)?.as_int()?;
+    let res = lhs.checked_div(rhs).ok_or(ForthError::DivByZero)?;
+    stack.push(Cell::Int(res));
+    Ok(())
 }
 
-fn print(stack: &mut Vec<i64>) -> Result<(), ForthError> {
-    match stack.last() {
-        Some(n) => {
+fn print(stack: &mut Vec<Cell>) -> Result<(), ForthError> {
+    match stack.pop() {
+        Some(Cell::Int(n)) => {
             println!("{}", n);
             Ok(())
         }
+        Some(Cell::Str(s)) => {
+            println!("{}", s);
+            Ok(())
+        }
         None => Err(ForthError::StackUnderflow),
     }
 }
 
-fn dup(stack: &mut Vec<i64>) -> Result<(), ForthError> {
+fn dup(stack: &mut Vec<Cell>) -> Result<(), ForthError> {
     match stack.last() {
-        Some(n) => {
-            stack.push(n.clone());
+        Some(cell) => {
+            stack.push(cell.clone());
             Ok(())
         }
         None => Err(ForthError::StackUnderflow),
     }
 }
 
+#[allow(clippy::ptr_arg)] // must match the `Definition::Native` fn-pointer signature
+fn cells(stack: &mut Vec<Cell>) -> Result<(), ForthError> {
+    // Memory is already addressed in `i64` cells, so converting a cell
+    // count to an address offset is a no-op; kept as a word for the
+    // familiar `n cells allot` idiom.
+    stack.last().ok_or(ForthError::StackUnderflow)?.as_int()?;
+    Ok(())
+}
+
+fn allot(stack: &mut Vec<Cell>, memory: &mut Vec<i64>) -> Result<(), ForthError> {
+    let n = stack.pop().ok_or(ForthError::StackUnderflow)?.as_int()?;
+    if n > 0 {
+        memory.resize(memory.len() + n as usize, 0);
+    }
+    Ok(())
+}
+
+#[allow(clippy::ptr_arg)] // must match the `Definition::NativeMem` fn-pointer signature
+fn fetch(stack: &mut Vec<Cell>, memory: &mut Vec<i64>) -> Result<(), ForthError> {
+    let addr = stack.pop().ok_or(ForthError::StackUnderflow)?.as_int()?;
+    let value = *memory
+        .get(addr as usize)
+        .ok_or(ForthError::InvalidAddress)?;
+    stack.push(Cell::Int(value));
+    Ok(())
+}
+
+#[allow(clippy::ptr_arg)] // must match the `Definition::NativeMem` fn-pointer signature
+fn store(stack: &mut Vec<Cell>, memory: &mut Vec<i64>) -> Result<(), ForthError> {
+    let addr = stack.pop().ok_or(ForthError::StackUnderflow)?.as_int()?;
+    let value = stack.pop().ok_or(ForthError::StackUnderflow)?.as_int()?;
+    let cell = memory
+        .get_mut(addr as usize)
+        .ok_or(ForthError::InvalidAddress)?;
+    *cell = value;
+    Ok(())
+}
+
+#[allow(clippy::ptr_arg)] // must match the `Definition::NativeMem` fn-pointer signature
+fn add_store(stack: &mut Vec<Cell>, memory: &mut Vec<i64>) -> Result<(), ForthError> {
+    let addr = stack.pop().ok_or(ForthError::StackUnderflow)?.as_int()?;
+    let value = stack.pop().ok_or(ForthError::StackUnderflow)?.as_int()?;
+    let cell = memory
+        .get_mut(addr as usize)
+        .ok_or(ForthError::InvalidAddress)?;
+    *cell += value;
+    Ok(())
+}
+
+fn concat(stack: &mut Vec<Cell>) -> Result<(), ForthError> {
+    let rhs = stack.pop().ok_or(ForthError::StackUnderflow)?;
+    let lhs = stack.pop().ok_or(ForthError::StackUnderflow)?;
+    match (lhs, rhs) {
+        (Cell::Str(mut lhs), Cell::Str(rhs)) => {
+            lhs.push_str(&rhs);
+            stack.push(Cell::Str(lhs));
+            Ok(())
+        }
+        _ => Err(ForthError::TypeMismatch),
+    }
+}
+
 impl Machine {
     pub fn new() -> Self {
         let stack = vec![];
@@ -140,10 +419,11 @@ impl Machine {
         definitions.insert("/".to_owned(), Definition::Native(div));
         definitions.insert("dup".to_owned(), Definition::Native(dup));
         definitions.insert(".".to_owned(), Definition::Native(print));
+        definitions.insert("concat".to_owned(), Definition::Native(concat));
         definitions.insert(
             "drop".to_owned(),
             Definition::Native(|stack| {
-                stack.pop();
+                stack.pop().ok_or(ForthError::StackUnderflow)?;
                 Ok(())
             }),
         );
@@ -162,94 +442,263 @@ impl Machine {
             }),
         );
         definitions.insert(
-            "=".to_owned(),
+            "over".to_owned(),
             Definition::Native(|stack| {
-                if let (Some(rhs), Some(lhs)) = (stack.pop(), stack.pop()) {
-                    stack.push(if lhs == rhs { 1 } else { 0 });
-                    Ok(())
-                } else {
-                    Err(ForthError::StackUnderflow)
+                let cell = stack
+                    .len()
+                    .checked_sub(2)
+                    .and_then(|i| stack.get(i))
+                    .ok_or(ForthError::StackUnderflow)?
+                    .clone();
+                stack.push(cell);
+                Ok(())
+            }),
+        );
+        definitions.insert(
+            "rot".to_owned(),
+            Definition::Native(|stack| {
+                let len = stack.len();
+                if len < 3 {
+                    return Err(ForthError::StackUnderflow);
                 }
+                let cell = stack.remove(len - 3);
+                stack.push(cell);
+                Ok(())
+            }),
+        );
+        definitions.insert(
+            "nip".to_owned(),
+            Definition::Native(|stack| {
+                let top = stack.pop().ok_or(ForthError::StackUnderflow)?;
+                stack.pop().ok_or(ForthError::StackUnderflow)?;
+                stack.push(top);
+                Ok(())
+            }),
+        );
+        definitions.insert(
+            "=".to_owned(),
+            Definition::Native(|stack| {
+                let rhs = stack.pop().ok_or(ForthError::StackUnderflow)?.as_int()?;
+                let lhs = stack.pop().ok_or(ForthError::StackUnderflow)?.as_int()?;
+                stack.push(Cell::Int(if lhs == rhs { 1 } else { 0 }));
+                Ok(())
             }),
         );
         definitions.insert(
             "<>".to_owned(),
             Definition::Native(|stack| {
-                if let (Some(rhs), Some(lhs)) = (stack.pop(), stack.pop()) {
-                    stack.push(if lhs != rhs { 1 } else { 0 });
-                    Ok(())
-                } else {
-                    Err(ForthError::StackUnderflow)
-                }
+                let rhs = stack.pop().ok_or(ForthError::StackUnderflow)?.as_int()?;
+                let lhs = stack.pop().ok_or(ForthError::StackUnderflow)?.as_int()?;
+                stack.push(Cell::Int(if lhs != rhs { 1 } else { 0 }));
+                Ok(())
             }),
         );
         definitions.insert(
             "<".to_owned(),
             Definition::Native(|stack| {
-                if let (Some(rhs), Some(lhs)) = (stack.pop(), stack.pop()) {
-                    stack.push(if lhs < rhs { 1 } else { 0 });
-                    Ok(())
-                } else {
-                    Err(ForthError::StackUnderflow)
-                }
+                let rhs = stack.pop().ok_or(ForthError::StackUnderflow)?.as_int()?;
+                let lhs = stack.pop().ok_or(ForthError::StackUnderflow)?.as_int()?;
+                stack.push(Cell::Int(if lhs < rhs { 1 } else { 0 }));
+                Ok(())
             }),
         );
         definitions.insert(
             ">".to_owned(),
             Definition::Native(|stack| {
-                if let (Some(rhs), Some(lhs)) = (stack.pop(), stack.pop()) {
-                    stack.push(if lhs > rhs { 1 } else { 0 });
-                    Ok(())
-                } else {
-                    Err(ForthError::StackUnderflow)
-                }
+                let rhs = stack.pop().ok_or(ForthError::StackUnderflow)?.as_int()?;
+                let lhs = stack.pop().ok_or(ForthError::StackUnderflow)?.as_int()?;
+                stack.push(Cell::Int(if lhs > rhs { 1 } else { 0 }));
+                Ok(())
             }),
         );
-        Self { stack, definitions }
+        definitions.insert("cells".to_owned(), Definition::Native(cells));
+        definitions.insert("allot".to_owned(), Definition::NativeMem(allot));
+        definitions.insert("@".to_owned(), Definition::NativeMem(fetch));
+        definitions.insert("!".to_owned(), Definition::NativeMem(store));
+        definitions.insert("+!".to_owned(), Definition::NativeMem(add_store));
+        Self {
+            stack,
+            definitions,
+            loop_stack: vec![],
+            memory: vec![],
+            include_stack: vec![],
+        }
+    }
+
+    /// Resolves `raw_path` relative to whichever file is currently being
+    /// included (or the working directory, for the first include), then
+    /// reads, lexes, and executes it in this `Machine`. Rejects a file that
+    /// is already in the include chain to avoid infinite recursion.
+    fn include(&mut self, raw_path: &str) -> Result<(), ForthError> {
+        let err = |e: std::io::Error| ForthError::IncludeError(raw_path.to_owned(), e.to_string());
+
+        let base_dir = self
+            .include_stack
+            .last()
+            .and_then(|p| p.parent())
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        let path = base_dir.join(raw_path).canonicalize().map_err(err)?;
+
+        if self.include_stack.contains(&path) {
+            return Err(ForthError::IncludeError(
+                raw_path.to_owned(),
+                "circular include".to_owned(),
+            ));
+        }
+
+        let source = std::fs::read_to_string(&path).map_err(err)?;
+        self.include_stack.push(path);
+        let result = self.lex(&source).and_then(|tokens| self.exec(tokens));
+        self.include_stack.pop();
+        result
+    }
+
+    /// Compiles and runs any tokens lexed so far but not yet executed,
+    /// draining `tokens` in the process. `variable` and `constant` call this
+    /// before touching `memory` or the data stack, so a runtime effect that
+    /// textually precedes them (an `allot`, or the arithmetic feeding a
+    /// `constant`) has actually happened rather than being deferred to the
+    /// end of the whole lexing pass.
+    fn flush_pending(&mut self, tokens: &mut Vec<Token>) -> Result<(), ForthError> {
+        if tokens.is_empty() {
+            return Ok(());
+        }
+        let pending = std::mem::take(tokens);
+        self.exec(pending)
+    }
+
+    /// Bump-allocates `n` fresh cells and returns the address of the first.
+    fn alloc(&mut self, n: usize) -> usize {
+        let addr = self.memory.len();
+        self.memory.resize(addr + n, 0);
+        addr
+    }
+
+    /// Resolves a single word against number literals and keywords, in that
+    /// order; anything else becomes a `Token::Word` looked up against
+    /// `definitions` at call time rather than here, so a word doesn't need
+    /// to exist yet when it's lexed (only when it's actually called). Word
+    /// lookup is case-insensitive, so `DUP`, `dup` and `Dup` all resolve to
+    /// the same definition.
+    fn lex_word(&self, word: &str) -> Result<Token, ForthError> {
+        if let Ok(number) = word.parse::<i64>() {
+            return Ok(Token::Number(number));
+        }
+        let word = word.to_lowercase();
+        if let Ok(keyword) = Keyword::try_from(word.as_str()) {
+            return Ok(Token::Keyword(keyword));
+        }
+        Ok(Token::Word(word))
+    }
+
+    /// Resolves a pre-tokenized raw word into a `Token`, passing quoted
+    /// literals through untouched.
+    fn raw_to_token(&self, raw: RawWord) -> Result<Token, ForthError> {
+        match raw {
+            RawWord::Word(word) => self.lex_word(&word),
+            RawWord::PrintLit(s) => Ok(Token::PrintLit(s)),
+            RawWord::PushLit(s) => Ok(Token::PushLit(s)),
+        }
     }
 
     pub fn lex(&mut self, input: &str) -> Result<Vec<Token>, ForthError> {
         enum LexMode {
             Interpreting,
-            Defining(VecDeque<String>),
+            Defining(VecDeque<RawWord>),
         }
         let mut tokens = vec![];
         let mut mode = LexMode::Interpreting;
+        let mut words = tokenize_raw(input)?.into_iter();
 
-        for word in input.split_whitespace() {
+        while let Some(raw) = words.next() {
             match &mut mode {
                 LexMode::Interpreting => {
-                    if let Ok(number) = word.parse::<i64>() {
-                        tokens.push(Token::Number(number));
+                    let word = match raw {
+                        RawWord::Word(word) => word,
+                        RawWord::PrintLit(s) => {
+                            tokens.push(Token::PrintLit(s));
+                            continue;
+                        }
+                        RawWord::PushLit(s) => {
+                            tokens.push(Token::PushLit(s));
+                            continue;
+                        }
+                    };
+                    if word == ":" {
+                        mode = LexMode::Defining(VecDeque::new());
                         continue;
                     }
-                    if let Some(def) = self.definitions.get(word) {
-                        tokens.push(Token::Op(def.clone()));
+                    if word.eq_ignore_ascii_case("variable") {
+                        // Flush whatever's pending first, so an `allot` earlier
+                        // in the same buffer bumps `memory` before this word
+                        // claims its cell — otherwise the two would race for
+                        // the same address instead of sharing one timeline.
+                        self.flush_pending(&mut tokens)?;
+                        let name = match words.next() {
+                            Some(RawWord::Word(name)) => name.to_lowercase(),
+                            _ => return Err(ForthError::MalformedDefinition("variable")),
+                        };
+                        let addr = self.alloc(1);
+                        self.definitions.insert(
+                            name,
+                            Definition::Compiled(Rc::new(vec![Instr::Push(Cell::Int(
+                                addr as i64,
+                            ))])),
+                        );
                         continue;
                     }
-
-                    if let Ok(keyword) = Keyword::try_from(word) {
-                        tokens.push(Token::Keyword(keyword));
+                    if word.eq_ignore_ascii_case("constant") {
+                        // As with `variable`, flush pending tokens so the
+                        // value is the actual result of running whatever
+                        // precedes `constant` (e.g. `10 5 + constant x`),
+                        // not just the last literal token seen.
+                        self.flush_pending(&mut tokens)?;
+                        let value = self
+                            .stack
+                            .pop()
+                            .ok_or(ForthError::StackUnderflow)?
+                            .as_int()?;
+                        let name = match words.next() {
+                            Some(RawWord::Word(name)) => name.to_lowercase(),
+                            _ => return Err(ForthError::MalformedDefinition("constant")),
+                        };
+                        self.definitions.insert(
+                            name,
+                            Definition::Compiled(Rc::new(vec![Instr::Push(Cell::Int(value))])),
+                        );
                         continue;
                     }
-
-                    if word == ":" {
-                        mode = LexMode::Defining(VecDeque::new());
+                    if word.eq_ignore_ascii_case("include") {
+                        let raw_path = match words.next() {
+                            Some(RawWord::Word(w)) => w,
+                            _ => return Err(ForthError::MalformedDefinition("include")),
+                        };
+                        let path = raw_path.trim_matches('"').to_owned();
+                        // Run the include immediately, like `variable`/`constant`,
+                        // so words it defines are visible to the rest of this
+                        // lexing pass rather than only at runtime.
+                        self.include(&path)?;
                         continue;
                     }
-
-                    return Err(ForthError::WordNotDefined(word.to_owned()));
+                    tokens.push(self.lex_word(&word)?);
                 }
                 LexMode::Defining(current) => {
-                    if word == ";" {
-                        let name = current.pop_front();
-                        let spaced_back = itertools::join(current, " ");
-                        let definition = Definition::Tokens(spaced_back);
-                        self.definitions.insert(name.unwrap(), definition);
-                        eprintln!("Defs: {:?}", &self.definitions);
+                    if matches!(&raw, RawWord::Word(w) if w == ";") {
+                        let name = match current.pop_front() {
+                            Some(RawWord::Word(name)) => name.to_lowercase(),
+                            _ => return Err(ForthError::MalformedDefinition(":")),
+                        };
+                        let body_tokens = current
+                            .drain(..)
+                            .map(|raw| self.raw_to_token(raw))
+                            .collect::<Result<Vec<_>, _>>()?;
+                        let instrs = compile(body_tokens)?;
+                        self.definitions
+                            .insert(name, Definition::Compiled(Rc::new(instrs)));
                         mode = LexMode::Interpreting;
                     } else {
-                        current.push_back(word.to_owned());
+                        current.push_back(raw);
                     }
                 }
             }
@@ -258,155 +707,233 @@ impl Machine {
     }
 
     pub fn exec(&mut self, tokens: Vec<Token>) -> Result<(), ForthError> {
-        struct CaptureMode {
-            pub tokens: Vec<Token>,
-            pub capture: bool,
-        }
+        // Every `DO`/`LOOP` frame opened while running these tokens is also
+        // closed while running them; a frame can only outlive this call if
+        // an earlier top-level call errored out from inside a loop body and
+        // left its frame behind. Clear it so that stale frame can't leak
+        // into this one (e.g. `i` resolving against a loop that aborted).
+        self.loop_stack.clear();
+        let instrs = compile(tokens)?;
+        self.run_instrs(&instrs)
+    }
 
-        impl CaptureMode {
-            fn push(&mut self, token: Token) {
-                if self.capture {
-                    self.tokens.push(token);
+    fn run_instrs(&mut self, instrs: &[Instr]) -> Result<(), ForthError> {
+        let mut pc = 0;
+        while pc < instrs.len() {
+            match &instrs[pc] {
+                Instr::Push(cell) => {
+                    self.stack.push(cell.clone());
+                    pc += 1;
                 }
-            }
-        }
-
-        enum ExecMode {
-            Normal,
-            IfTrue(CaptureMode),
-            IfFalse(CaptureMode),
-        }
-
-        let mut mode_stack = vec!();
-        mode_stack.push(ExecMode::Normal);
-
-        for token in tokens {
-            let mut mode = mode_stack.last_mut().unwrap();
-            match (mode, token) {
-                (ExecMode::Normal, Token::Number(n)) => self.stack.push(n),
-                (ExecMode::Normal, Token::Op(def)) => self.run(def)?,
-                (ExecMode::Normal, Token::Keyword(kw)) => match kw {
-                    Keyword::If => {
-                        if let Some(condition) = self.stack.pop() {
-                            if condition != 0 {
-                                mode_stack.push(ExecMode::IfTrue(CaptureMode {
-                                    tokens: vec![],
-                                    capture: true,
-                                }));
-                            } else {
-                                mode_stack.push(ExecMode::IfFalse(CaptureMode {
-                                    tokens: vec![],
-                                    capture: false,
-                                }));
-                            }
-                        } else {
-                            return Err(ForthError::StackUnderflow);
-                        }
-                    }
-                    Keyword::Else | Keyword::Then => {
-                        todo!(); // This is an error
-                    }
-                    Keyword::Do => {
-                        todo!();
-                    }
-                },
-                (ExecMode::IfTrue(tokens), Token::Number(n)) => tokens.push(Token::Number(n)),
-                (ExecMode::IfTrue(tokens), Token::Op(op)) => tokens.push(Token::Op(op)),
-                (ExecMode::IfFalse(tokens), Token::Number(n)) => tokens.push(Token::Number(n)),
-                (ExecMode::IfFalse(tokens), Token::Op(op)) => tokens.push(Token::Op(op)),
-                (ExecMode::IfFalse(tokens), Token::Keyword(kw)) => match kw {
-                    Keyword::Else => tokens.capture = true,
-                    Keyword::Then => {
-                        // Cheeky swap the mode around
-                        let old = mode_stack.pop();
-                        // Force old into a IfFalse, we know thats what it is
-                        if let Some(ExecMode::IfFalse(old)) = old {
-                            self.exec(old.tokens)?;
-                        } else {
-                            unreachable!();
-                        }
-                    },
-                    Keyword::If => {
-                        if let Some(condition) = self.stack.pop() {
-                            if condition != 0 {
-                                mode_stack.push(ExecMode::IfTrue(CaptureMode {
-                                    tokens: vec![],
-                                    capture: true,
-                                }));
-                            } else {
-                                mode_stack.push(ExecMode::IfFalse(CaptureMode {
-                                    tokens: vec![],
-                                    capture: false,
-                                }));
-                            }
-                        } else {
-                            return Err(ForthError::StackUnderflow);
-                        }
-                    }
-                    Keyword::Do => {
-                        todo!();
-                    }
-                },
-                (ExecMode::IfTrue(tokens), Token::Keyword(kw)) => match kw {
-                    Keyword::Else => tokens.capture = false,
-                    Keyword::Then => {
-                        let old = mode_stack.pop();
-                        if let Some(ExecMode::IfTrue(old)) = old {
-                            self.exec(old.tokens)?;
-                        } else {
-                            unreachable!();
-                        }
+                Instr::Call(name) => {
+                    let def = self
+                        .definitions
+                        .get(name)
+                        .cloned()
+                        .ok_or_else(|| ForthError::WordNotDefined(name.clone()))?;
+                    self.run(def)?;
+                    pc += 1;
+                }
+                Instr::Jump(target) => pc = *target,
+                Instr::JumpUnless(target) => {
+                    let condition = self
+                        .stack
+                        .pop()
+                        .ok_or(ForthError::StackUnderflow)?
+                        .as_int()?;
+                    if condition == 0 {
+                        pc = *target;
+                    } else {
+                        pc += 1;
                     }
-                    Keyword::If => {
-                        if let Some(condition) = self.stack.pop() {
-                            if condition != 0 {
-                                mode_stack.push(ExecMode::IfTrue(CaptureMode {
-                                    tokens: vec![],
-                                    capture: true,
-                                }));
-                            } else {
-                                mode_stack.push(ExecMode::IfFalse(CaptureMode {
-                                    tokens: vec![],
-                                    capture: false,
-                                }));
-                            }
-                        } else {
-                            return Err(ForthError::StackUnderflow);
-                        }
-                    },
-                    Keyword::Do => {
-                        todo!();
+                }
+                Instr::LoopEnter => {
+                    let start = self
+                        .stack
+                        .pop()
+                        .ok_or(ForthError::StackUnderflow)?
+                        .as_int()?;
+                    let limit = self
+                        .stack
+                        .pop()
+                        .ok_or(ForthError::StackUnderflow)?
+                        .as_int()?;
+                    self.loop_stack.push((start, limit));
+                    pc += 1;
+                }
+                Instr::LoopNext(body_start) => {
+                    let frame = self
+                        .loop_stack
+                        .last_mut()
+                        .ok_or(ForthError::UnbalancedLoop)?;
+                    frame.0 += 1;
+                    if frame.0 < frame.1 {
+                        pc = *body_start;
+                    } else {
+                        self.loop_stack.pop();
+                        pc += 1;
                     }
-                },
+                }
+                Instr::PushIndex => {
+                    let (index, _) = self.loop_stack.last().ok_or(ForthError::UnbalancedLoop)?;
+                    self.stack.push(Cell::Int(*index));
+                    pc += 1;
+                }
+                Instr::PrintLiteral(s) => {
+                    println!("{}", s);
+                    pc += 1;
+                }
             }
         }
-
         Ok(())
     }
 
     pub fn run(&mut self, definition: Definition) -> Result<(), ForthError> {
         match definition {
             Definition::Native(func) => func(&mut self.stack)?,
-            Definition::Tokens(toks) => {
-                let toks = self.lex(&toks)?;
-                self.exec(toks)?;
-            }
+            Definition::NativeMem(func) => func(&mut self.stack, &mut self.memory)?,
+            Definition::Compiled(instrs) => self.run_instrs(&instrs)?,
         }
         Ok(())
     }
 }
 
+/// A `rustyline` `Helper` that completes against known words, validates
+/// multi-line input (an open `:` definition or `if`), and hints the current
+/// stack contents as a dim suffix. Shares the `Machine` with the main loop
+/// so completion and hinting always reflect live state.
+struct ForthHelper {
+    machine: Rc<RefCell<Machine>>,
+}
+
+impl Completer for ForthHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos]
+            .rfind(char::is_whitespace)
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let prefix = &line[start..pos];
+        let candidates = self
+            .machine
+            .borrow()
+            .definitions
+            .keys()
+            .filter(|word| word.starts_with(prefix))
+            .cloned()
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ForthHelper {
+    type Hint = String;
+
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Option<String> {
+        if pos < line.len() {
+            return None;
+        }
+        Some(format!("  {:?}", self.machine.borrow().stack))
+    }
+}
+
+impl Highlighter for ForthHelper {
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Cow::Owned(format!("\x1b[2m{}\x1b[0m", hint))
+    }
+}
+
+impl Validator for ForthHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        if has_unclosed_block(ctx.input()) {
+            Ok(ValidationResult::Incomplete)
+        } else {
+            Ok(ValidationResult::Valid(None))
+        }
+    }
+}
+
+impl Helper for ForthHelper {}
+
+/// Cheap unclosed-block check used by the `Validator`: true if `input` has
+/// an open `:` definition or an `if` with no matching `then`. Goes through
+/// `tokenize_raw` rather than a second ad-hoc split so a `."`/`s"` literal's
+/// contents (which may themselves contain `if`, `:`, etc.) are skipped
+/// instead of thrown off the depth count.
+fn has_unclosed_block(input: &str) -> bool {
+    let words = match tokenize_raw(input) {
+        Ok(words) => words,
+        // An unterminated `."`/`s"` just means the user isn't done typing
+        // the literal yet; let them keep going rather than erroring here.
+        Err(_) => return true,
+    };
+    let mut colon_depth = 0i32;
+    let mut if_depth = 0i32;
+    for word in words {
+        let word = match word {
+            RawWord::Word(word) => word,
+            RawWord::PrintLit(_) | RawWord::PushLit(_) => continue,
+        };
+        match word.to_lowercase().as_str() {
+            ":" => colon_depth += 1,
+            ";" => colon_depth -= 1,
+            "if" => if_depth += 1,
+            "then" => if_depth -= 1,
+            _ => {}
+        }
+    }
+    colon_depth > 0 || if_depth > 0
+}
+
+/// A Forth interpreter. With no arguments it drops straight into the REPL;
+/// given a script it runs that non-interactively unless `--interactive` is
+/// also set.
+#[derive(Parser)]
+struct Cli {
+    /// Forth source file to run.
+    script: Option<PathBuf>,
+    /// Drop into the REPL after running `script`, keeping its definitions.
+    #[arg(long)]
+    interactive: bool,
+}
+
 fn main() -> Result<(), anyhow::Error> {
-    let mut machine = Machine::new();
-    let mut rl = DefaultEditor::new()?;
+    let cli = Cli::parse();
+    let machine = Rc::new(RefCell::new(Machine::new()));
+
+    if let Some(script) = &cli.script {
+        let path = script.to_string_lossy().into_owned();
+        if let Err(err) = machine.borrow_mut().include(&path) {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+        if !cli.interactive {
+            return Ok(());
+        }
+    }
+
+    let mut rl = Editor::<ForthHelper, DefaultHistory>::new()?;
+    rl.set_helper(Some(ForthHelper {
+        machine: machine.clone(),
+    }));
 
     loop {
         let readline = rl.readline(">> ");
         match readline {
             Ok(line) => {
-                let toks = machine.lex(&line)?;
-                machine.exec(toks)?;
-                eprintln!("{:?}", machine.stack);
+                rl.add_history_entry(line.as_str())?;
+                let mut machine = machine.borrow_mut();
+                let result = machine.lex(&line).and_then(|toks| machine.exec(toks));
+                match result {
+                    Ok(()) => eprintln!("{:?}", machine.stack),
+                    Err(err) => eprintln!("Error: {}", err),
+                }
             }
             Err(ReadlineError::Interrupted) => {
                 eprintln!("Terminated");
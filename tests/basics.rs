@@ -58,6 +58,22 @@ fn define() {
         .stdout(predicates::str::diff("20\n"));
 }
 
+#[test]
+fn recursive_definition_calls_itself() {
+    make_command()
+        .write_stdin(": fact dup 1 > if dup 1 - fact * then ; 5 fact .")
+        .assert()
+        .stdout(predicates::str::diff("120\n"));
+}
+
+#[test]
+fn forward_reference_between_definitions() {
+    make_command()
+        .write_stdin(": a b ; : b 1 ; a .")
+        .assert()
+        .stdout(predicates::str::diff("1\n"));
+}
+
 #[test]
 fn duplication() {
     make_command()
@@ -98,6 +114,126 @@ fn addition() {
         .stdout(predicates::str::diff("30\n"));
 }
 
+#[test]
+fn counted_loop() {
+    make_command()
+        .write_stdin("5 0 do i . loop")
+        .assert()
+        .stdout(predicates::str::diff("0\n1\n2\n3\n4\n"));
+}
+
+#[test]
+fn begin_while_repeat() {
+    make_command()
+        .write_stdin(": count-up 0 begin dup 5 < while dup . 1 + repeat drop ; count-up")
+        .assert()
+        .stdout(predicates::str::diff("0\n1\n2\n3\n4\n"));
+}
+
+#[test]
+fn begin_until() {
+    make_command()
+        .write_stdin(": count-up 0 begin dup . 1 + dup 5 = until drop ; count-up")
+        .assert()
+        .stdout(predicates::str::diff("0\n1\n2\n3\n4\n"));
+}
+
+#[test]
+fn variable_store_and_fetch() {
+    make_command()
+        .write_stdin("variable x 10 x ! x @ .")
+        .assert()
+        .stdout(predicates::str::diff("10\n"));
+}
+
+#[test]
+fn constant_pushes_value() {
+    make_command()
+        .write_stdin("42 constant answer answer .")
+        .assert()
+        .stdout(predicates::str::diff("42\n"));
+}
+
+#[test]
+fn plus_store_accumulates() {
+    make_command()
+        .write_stdin("variable x 10 x ! 5 x +! x @ .")
+        .assert()
+        .stdout(predicates::str::diff("15\n"));
+}
+
+#[test]
+fn dot_quote_prints_literal() {
+    make_command()
+        .write_stdin(".\" hello world\"")
+        .assert()
+        .stdout(predicates::str::diff("hello world\n"));
+}
+
+#[test]
+fn s_quote_pushes_string() {
+    make_command()
+        .write_stdin("s\" hello\" .")
+        .assert()
+        .stdout(predicates::str::diff("hello\n"));
+}
+
+#[test]
+fn concat_joins_strings() {
+    make_command()
+        .write_stdin("s\" hello \" s\" world\" concat .")
+        .assert()
+        .stdout(predicates::str::diff("hello world\n"));
+}
+
+#[test]
+fn over_duplicates_second_item() {
+    make_command()
+        .write_stdin("1 2 over . .")
+        .assert()
+        .stdout(predicates::str::diff("1\n2\n"));
+}
+
+#[test]
+fn rot_rotates_third_item_to_top() {
+    make_command()
+        .write_stdin("1 2 3 rot . . .")
+        .assert()
+        .stdout(predicates::str::diff("1\n3\n2\n"));
+}
+
+#[test]
+fn nip_drops_second_item() {
+    make_command()
+        .write_stdin("1 2 nip .")
+        .assert()
+        .stdout(predicates::str::diff("2\n"));
+}
+
+#[test]
+fn words_are_case_insensitive() {
+    make_command()
+        .write_stdin("10 DUP + .")
+        .assert()
+        .stdout(predicates::str::diff("20\n"));
+}
+
+#[test]
+fn non_whitespace_separators_are_honoured() {
+    make_command()
+        .write_stdin("10\x0020\x01+\x00.")
+        .assert()
+        .stdout(predicates::str::diff("30\n"));
+}
+
+#[test]
+fn run_script_file() {
+    make_command()
+        .arg("tests/fixtures/main.fth")
+        .assert()
+        .stdout(predicates::str::diff("20\n"));
+}
+
 #[test]
 fn it_runs() {
     make_command().assert().success();